@@ -0,0 +1,321 @@
+use std::alloc::{GlobalAlloc, Layout};
+use std::ptr;
+use std::sync::Mutex;
+
+pub const DEFAULT_SIZE: usize = 128 * 1024;
+pub const DEFAULT_GRAIN: usize = 64;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+// some headroom beyond a single grain's alignment so the arena's base address doesn't
+// itself become the reason a well-aligned request can't be satisfied
+#[repr(align(4096))]
+struct Arena<const SIZE: usize>([u8; SIZE]);
+
+impl<const SIZE: usize> Arena<SIZE> {
+    const fn new() -> Self {
+        Self([0x00; SIZE])
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.0.as_mut_ptr()
+    }
+}
+
+pub struct BitmapAlloc<const SIZE: usize, const GRAIN: usize> {
+    details: Mutex<BitmapImpl<SIZE, GRAIN>>,
+}
+
+impl<const SIZE: usize, const GRAIN: usize> BitmapAlloc<SIZE, GRAIN> {
+    pub const fn new() -> Self {
+        Self {
+            details: Mutex::new(BitmapImpl::<SIZE, GRAIN>::new()),
+        }
+    }
+
+    #[cfg(test)]
+    fn num_allocated_slots(&self) -> usize {
+        self.details.lock().unwrap().num_allocated_slots()
+    }
+}
+
+unsafe impl<const SIZE: usize, const GRAIN: usize> GlobalAlloc for BitmapAlloc<SIZE, GRAIN> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Ok(mut details) = self.details.lock() {
+            details.alloc(layout)
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Ok(mut details) = self.details.lock() {
+            details.dealloc(ptr, layout);
+        }
+    }
+}
+
+struct BitmapImpl<const SIZE: usize, const GRAIN: usize> {
+    arena: Arena<SIZE>,
+    // one bit per GRAIN-byte slot, packed into u64 words. a const generic array length
+    // can't be computed from SIZE/GRAIN on stable rust, so this starts empty and is
+    // lazily sized to exactly `word_count()` words on first use instead of reserving a
+    // worst-case bound up front (mirrors the `head: Option<ListNode>` lazy-init trick in
+    // freelist.rs).
+    words: Vec<u64>,
+}
+
+impl<const SIZE: usize, const GRAIN: usize> BitmapImpl<SIZE, GRAIN> {
+    const fn new() -> Self {
+        Self {
+            arena: Arena::new(),
+            words: Vec::new(),
+        }
+    }
+
+    const fn slot_count() -> usize {
+        SIZE / GRAIN
+    }
+
+    const fn word_count() -> usize {
+        Self::slot_count().div_ceil(WORD_BITS)
+    }
+
+    fn slots_for(layout: Layout) -> usize {
+        layout.pad_to_align().size().div_ceil(GRAIN)
+    }
+
+    fn ensure_initialized(&mut self) {
+        if self.words.is_empty() {
+            self.words.resize(Self::word_count(), 0);
+        }
+    }
+
+    unsafe fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let needed = Self::slots_for(layout);
+        if needed == 0 || needed > Self::slot_count() {
+            return ptr::null_mut();
+        }
+        self.ensure_initialized();
+        let arena_base = self.arena.as_mut_ptr() as usize;
+        let Some(start) = find_free_run(
+            &self.words,
+            Self::slot_count(),
+            needed,
+            arena_base,
+            GRAIN,
+            layout.align(),
+        ) else {
+            return ptr::null_mut();
+        };
+        mark_range(&mut self.words, start, needed, true);
+        self.arena.as_mut_ptr().add(start * GRAIN)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let needed = Self::slots_for(layout);
+        self.ensure_initialized();
+        let start = (ptr as usize - self.arena.as_mut_ptr() as usize) / GRAIN;
+        mark_range(&mut self.words, start, needed, false);
+    }
+
+    #[cfg(test)]
+    fn num_allocated_slots(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+fn mark_range(words: &mut [u64], start: usize, len: usize, occupied: bool) {
+    for bit in start..start + len {
+        let mask = 1u64 << (bit % WORD_BITS);
+        let word = &mut words[bit / WORD_BITS];
+        if occupied {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+}
+
+// the next free slot at or after `bit`, found via `trailing_zeros` on the word rather
+// than testing each bit in turn
+fn next_free_bit(words: &[u64], mut bit: usize, slot_count: usize) -> Option<usize> {
+    while bit < slot_count {
+        let word = words[bit / WORD_BITS];
+        let offset = bit % WORD_BITS;
+        let free_from_offset = !word >> offset;
+        if free_from_offset == 0 {
+            // fast path: nothing free left in this word, skip straight past it
+            bit += WORD_BITS - offset;
+            continue;
+        }
+        // fast path: jump straight to the next free bit via trailing_zeros
+        bit += free_from_offset.trailing_zeros() as usize;
+        return Some(bit).filter(|&bit| bit < slot_count);
+    }
+    None
+}
+
+// the length of the contiguous free run beginning at `start`, found a word at a time via
+// `trailing_ones` rather than testing each bit in turn
+fn free_run_len(words: &[u64], start: usize, slot_count: usize) -> usize {
+    let mut len = 0;
+    while start + len < slot_count {
+        let bit = start + len;
+        let word = words[bit / WORD_BITS];
+        let offset = bit % WORD_BITS;
+        let run_here = ((!word >> offset).trailing_ones() as usize).min(slot_count - bit);
+        len += run_here;
+        if offset + run_here < WORD_BITS {
+            // the run stopped short of the end of the word, so it stops here
+            break;
+        }
+    }
+    len
+}
+
+// the smallest slot within `start..start+len` whose arena address satisfies `align` and
+// still leaves room for `needed` slots, or None if the run isn't suitably aligned anywhere
+fn aligned_slot_in_run(
+    arena_base: usize,
+    grain: usize,
+    align: usize,
+    start: usize,
+    len: usize,
+    needed: usize,
+) -> Option<usize> {
+    (start..=start + (len - needed)).find(|&slot| (arena_base + slot * grain).is_multiple_of(align))
+}
+
+// scans for `needed` contiguous, suitably-aligned free slots, walking free runs via
+// `next_free_bit`/`free_run_len` rather than a plain bit-by-bit scan
+fn find_free_run(
+    words: &[u64],
+    slot_count: usize,
+    needed: usize,
+    arena_base: usize,
+    grain: usize,
+    align: usize,
+) -> Option<usize> {
+    let mut bit = 0;
+    while let Some(start) = next_free_bit(words, bit, slot_count) {
+        let len = free_run_len(words, start, slot_count);
+        if len >= needed {
+            if let Some(slot) = aligned_slot_in_run(arena_base, grain, align, start, len, needed) {
+                return Some(slot);
+            }
+        }
+        bit = start + len.max(1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SIZE: usize = 1024;
+    const TEST_GRAIN: usize = 8;
+
+    #[test]
+    fn allocates() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+    }
+
+    #[test]
+    fn provides_distinct_allocations() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes_1 = unsafe { alloc.alloc(layout) };
+        let bytes_2 = unsafe { alloc.alloc(layout) };
+        assert!(!ptr::eq(bytes_1, bytes_2));
+    }
+
+    #[test]
+    fn allocates_a_contiguous_run_of_slots() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(3 * TEST_GRAIN, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+        assert_eq!(alloc.num_allocated_slots(), 3);
+    }
+
+    #[test]
+    fn allocates_a_run_crossing_a_word_boundary() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(TEST_GRAIN, 4).unwrap();
+        // fill every slot one at a time, then free the pair straddling the boundary
+        // between the first two 64-bit words (slots 63 and 64), so the next allocation
+        // can only be satisfied by a run that crosses a word
+        let mut handles = Vec::new();
+        for _ in 0..TEST_SIZE / TEST_GRAIN {
+            handles.push(unsafe { alloc.alloc(layout) });
+        }
+        assert!(handles.iter().all(|bytes| !bytes.is_null()));
+        unsafe {
+            alloc.dealloc(handles[63], layout);
+            alloc.dealloc(handles[64], layout);
+        }
+        let double_layout = Layout::from_size_align(2 * TEST_GRAIN, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(double_layout) };
+        assert!(!bytes.is_null());
+        assert!(ptr::eq(bytes, handles[63]));
+    }
+
+    #[test]
+    fn frees_then_reuses_a_slot() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes_1 = unsafe { alloc.alloc(layout) };
+        unsafe { alloc.dealloc(bytes_1, layout) };
+        assert_eq!(alloc.num_allocated_slots(), 0);
+        let bytes_2 = unsafe { alloc.alloc(layout) };
+        assert!(ptr::eq(bytes_1, bytes_2));
+    }
+
+    #[test]
+    fn frees_an_interior_run_for_reuse() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(TEST_GRAIN, 4).unwrap();
+        let bytes_1 = unsafe { alloc.alloc(layout) };
+        let bytes_2 = unsafe { alloc.alloc(layout) };
+        let _bytes_3 = unsafe { alloc.alloc(layout) };
+        unsafe { alloc.dealloc(bytes_2, layout) };
+        let bytes_4 = unsafe { alloc.alloc(layout) };
+        assert!(ptr::eq(bytes_2, bytes_4));
+        assert!(!ptr::eq(bytes_1, bytes_4));
+    }
+
+    #[test]
+    fn fails_when_no_run_is_large_enough() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(TEST_SIZE + 1, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(bytes.is_null());
+    }
+
+    #[test]
+    fn honors_large_alignment() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        let layout = Layout::from_size_align(TEST_GRAIN, 128).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+        assert_eq!(bytes as usize % 128, 0);
+    }
+
+    #[test]
+    fn honors_alignment_when_run_start_is_misaligned() {
+        let alloc = BitmapAlloc::<TEST_SIZE, TEST_GRAIN>::new();
+        // shift the remaining free run off of a 128-byte boundary
+        let offsetting_layout = Layout::from_size_align(TEST_GRAIN, 4).unwrap();
+        let _shift = unsafe { alloc.alloc(offsetting_layout) };
+
+        let layout = Layout::from_size_align(TEST_GRAIN, 128).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+        assert_eq!(bytes as usize % 128, 0);
+    }
+}
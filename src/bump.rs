@@ -3,6 +3,11 @@ use std::cell::UnsafeCell;
 use std::ptr;
 use std::sync::Mutex;
 
+#[cfg(feature = "allocator_api")]
+use std::alloc::{AllocError, Allocator};
+#[cfg(feature = "allocator_api")]
+use std::ptr::NonNull;
+
 pub const DEFAULT_SIZE: usize = 128 * 1024;
 
 pub struct BumpAlloc<const SIZE: usize> {
@@ -27,6 +32,53 @@ impl<const SIZE: usize> BumpAlloc<SIZE> {
     fn is_clear(&self) -> bool {
         self.details.lock().unwrap().next == 0
     }
+
+    /// Reclaims the whole arena in one step, for callers that allocate a batch of values,
+    /// use them within some scope, and then want the space back.
+    ///
+    /// # Safety
+    ///
+    /// Sound only once every reference handed out by a prior `alloc`/`alloc_value`/
+    /// `alloc_slice` call is dead, since the next allocation is free to reuse that space.
+    /// `alloc_value`/`alloc_slice` hand out a `&mut` tied only to `&self`, so a caller could
+    /// otherwise keep one alive across a call to this and alias it against the next allocation.
+    pub unsafe fn reset(&self) {
+        if let Ok(mut details) = self.details.lock() {
+            details.next = 0;
+            details.allocations = 0;
+        }
+    }
+
+    fn alloc_layout(&self, layout: Layout) -> Option<*mut u8> {
+        let ptr = unsafe { GlobalAlloc::alloc(self, layout) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(ptr)
+        }
+    }
+
+    // like `alloc`, every returned reference points into memory this bump pointer will
+    // never hand out again until `reset`, so distinct calls can't alias each other
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_value<T>(&self, val: T) -> Option<&mut T> {
+        let ptr = self.alloc_layout(Layout::new::<T>())? as *mut T;
+        unsafe {
+            ptr.write(val);
+            Some(&mut *ptr)
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Default>(&self, len: usize) -> Option<&mut [T]> {
+        let ptr = self.alloc_layout(Layout::array::<T>(len).ok()?)? as *mut T;
+        unsafe {
+            for i in 0..len {
+                ptr.add(i).write(T::default());
+            }
+            Some(std::slice::from_raw_parts_mut(ptr, len))
+        }
+    }
 }
 
 // we're handing out non-overlapping chunks of the arena, and the rest is mutex-guarded
@@ -48,18 +100,37 @@ unsafe impl<const SIZE: usize> GlobalAlloc for BumpAlloc<SIZE> {
         }
     }
 
-    // concern: we can enter a state where space is allocated and then the next pointer is reset.
-    // this would allow us to hand out the same memory twice. which is bad.
+    // only tracks the outstanding count; reclaiming the arena on this path used to be a
+    // correctness hazard, since a leaked reference wouldn't decrement `allocations` but a
+    // surviving one could still be handed out again the moment the count hit zero. bulk
+    // reclamation now only happens through the explicit `reset`.
     unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
         if let Ok(mut details) = self.details.lock() {
             details.allocations -= 1;
-            if details.allocations == 0 {
-                details.next = 0;
-            }
         }
     }
 }
 
+#[cfg(feature = "allocator_api")]
+unsafe impl<const SIZE: usize> Allocator for BumpAlloc<SIZE> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { GlobalAlloc::alloc(self, layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        // pad_to_align is the actual size we carved out, which may exceed the request
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.pad_to_align().size()))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { GlobalAlloc::alloc_zeroed(self, layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.pad_to_align().size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        GlobalAlloc::dealloc(self, ptr.as_ptr(), layout)
+    }
+}
+
 struct BumpImpl {
     next: usize,
     allocations: usize,
@@ -108,13 +179,26 @@ mod tests {
     }
 
     #[test]
-    fn frees_allocations() {
+    fn dealloc_does_not_reclaim_space() {
         let bump = BumpAlloc::<DEFAULT_SIZE>::new();
         let layout = Layout::from_size_align(10, 4).unwrap();
         let bytes_1 = unsafe { bump.alloc(layout) };
         unsafe { bump.dealloc(bytes_1, layout) };
         let bytes_2 = unsafe { bump.alloc(layout) };
-        assert!(ptr::eq(bytes_1, bytes_2));
+        assert!(!ptr::eq(bytes_1, bytes_2));
+    }
+
+    #[test]
+    fn reset_reclaims_space_for_reuse() {
+        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes_1 = unsafe { bump.alloc(layout) };
+        let _bytes_2 = unsafe { bump.alloc(layout) };
+        unsafe { bump.reset() };
+        assert!(bump.is_clear());
+        assert_eq!(bump.num_allocated(), 0);
+        let bytes_3 = unsafe { bump.alloc(layout) };
+        assert!(ptr::eq(bytes_1, bytes_3));
     }
 
     #[test]
@@ -148,34 +232,6 @@ mod tests {
         }
     }
 
-    // ignoring due to how long it takes to run in successful cases
-    // run this test to check for alloc/dealloc contention
-    #[ignore]
-    #[test]
-    fn may_maintain_allocations() {
-        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
-        let layout = Layout::from_size_align(10, 4).unwrap();
-        let mut bytes = unsafe { bump.alloc(layout) } as usize;
-        for _ in 0..1000 {
-            bytes = std::thread::scope(|scope| {
-                let dealloc = scope.spawn(|| {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                    unsafe { bump.dealloc(bytes as *mut u8, layout) };
-                });
-                let alloc = scope.spawn(|| {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                    let bytes = unsafe { bump.alloc(layout) };
-                    bytes as usize
-                });
-                let bytes = alloc.join().expect("Allocation failed");
-                let _ = dealloc.join().expect("Deallocation failed");
-                bytes
-            });
-            assert_eq!(bump.num_allocated(), 1);
-            assert!(!bump.is_clear());
-        }
-    }
-
     #[test]
     fn may_fail_to_allocate() {
         let bump = BumpAlloc::<0>::new();
@@ -209,4 +265,70 @@ mod tests {
             assert!(byte == 0xff);
         }
     }
+
+    #[test]
+    fn alloc_value_returns_usable_reference() {
+        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
+        let value = bump.alloc_value(42u32).expect("Allocation failed");
+        assert_eq!(*value, 42);
+        *value = 7;
+        assert_eq!(*value, 7);
+    }
+
+    #[test]
+    fn alloc_value_fails_when_arena_is_full() {
+        let bump = BumpAlloc::<0>::new();
+        assert!(bump.alloc_value(42u32).is_none());
+    }
+
+    #[test]
+    fn alloc_slice_returns_usable_slice() {
+        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
+        let slice = bump.alloc_slice::<u32>(4).expect("Allocation failed");
+        assert_eq!(slice, [0, 0, 0, 0]);
+        slice[2] = 9;
+        assert_eq!(slice[2], 9);
+    }
+
+    #[test]
+    fn alloc_slice_fails_when_arena_is_full() {
+        let bump = BumpAlloc::<0>::new();
+        assert!(bump.alloc_slice::<u32>(4).is_none());
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocates_via_allocator_api() {
+        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let memory = bump.allocate(layout).expect("Allocation failed");
+        assert!(memory.len() >= layout.size());
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocator_api_reports_usable_capacity() {
+        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let memory = bump.allocate(layout).expect("Allocation failed");
+        assert_eq!(memory.len(), layout.pad_to_align().size());
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn deallocates_via_allocator_api() {
+        let bump = BumpAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let memory = bump.allocate(layout).expect("Allocation failed");
+        unsafe { bump.deallocate(memory.cast(), layout) };
+        assert_eq!(bump.num_allocated(), 0);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocator_api_fails_when_arena_is_full() {
+        let bump = BumpAlloc::<0>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        assert!(bump.allocate(layout).is_err());
+    }
 }
@@ -0,0 +1,138 @@
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::Mutex;
+
+use crate::freelist::FreeListAlloc;
+
+pub const DEFAULT_SIZE: usize = 128 * 1024;
+
+// powers of two from 8 up to 2048: large enough to cover most small allocations
+// without wasting too much space, small enough to keep the list array cheap
+const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+pub struct FixedSizeBlockAllocator<const SIZE: usize> {
+    // blocks too large for any class, and the first carve of each class, come from here
+    fallback: FreeListAlloc<SIZE>,
+    lists: Mutex<[Option<&'static mut ListNode>; BLOCK_SIZES.len()]>,
+}
+
+impl<const SIZE: usize> FixedSizeBlockAllocator<SIZE> {
+    pub const fn new() -> Self {
+        Self {
+            fallback: FreeListAlloc::new(),
+            lists: Mutex::new([None, None, None, None, None, None, None, None, None]),
+        }
+    }
+
+    // smallest class that can hold both the requested size and the requested alignment,
+    // since blocks we hand out aren't guaranteed aligned beyond their own size
+    fn list_index(layout: &Layout) -> Option<usize> {
+        let required = layout.size().max(layout.align());
+        BLOCK_SIZES.iter().position(|&size| size >= required)
+    }
+
+    #[cfg(test)]
+    fn free_list_length(&self, index: usize) -> usize {
+        let lists = self.lists.lock().unwrap();
+        let mut length = 0;
+        let mut current = &lists[index];
+        while let Some(node) = current {
+            length += 1;
+            current = &node.next;
+        }
+        length
+    }
+}
+
+unsafe impl<const SIZE: usize> GlobalAlloc for FixedSizeBlockAllocator<SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match Self::list_index(&layout) {
+            Some(index) => {
+                if let Ok(mut lists) = self.lists.lock() {
+                    if let Some(node) = lists[index].take() {
+                        lists[index] = node.next.take();
+                        return node as *mut ListNode as *mut u8;
+                    }
+                }
+                let block_size = BLOCK_SIZES[index];
+                let block_layout = Layout::from_size_align(block_size, block_size)
+                    .expect("Block size and alignment must be valid");
+                self.fallback.alloc(block_layout)
+            }
+            None => self.fallback.alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // every pointer handed out for a class was carved via `Layout::from_size_align(block_size,
+        // block_size)`, and the smallest block size is >= align_of::<ListNode>(), so the block is
+        // always suitable to reuse as a node
+        match Self::list_index(&layout) {
+            Some(index) => {
+                let new_node = ListNode { next: None };
+                let node_ptr = ptr as *mut ListNode;
+                node_ptr.write(new_node);
+                if let Ok(mut lists) = self.lists.lock() {
+                    let node = &mut *node_ptr;
+                    node.next = lists[index].take();
+                    lists[index] = Some(node);
+                }
+            }
+            None => self.fallback.dealloc(ptr, layout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn allocates() {
+        let alloc = FixedSizeBlockAllocator::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+    }
+
+    #[test]
+    fn reuses_freed_block() {
+        let alloc = FixedSizeBlockAllocator::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        unsafe { alloc.dealloc(bytes, layout) };
+        let more_bytes = unsafe { alloc.alloc(layout) };
+        assert!(ptr::eq(bytes, more_bytes));
+    }
+
+    #[test]
+    fn dealloc_populates_free_list() {
+        let alloc = FixedSizeBlockAllocator::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        unsafe { alloc.dealloc(bytes, layout) };
+        assert_eq!(alloc.free_list_length(1), 1);
+    }
+
+    #[test]
+    fn falls_back_for_oversized_allocations() {
+        let alloc = FixedSizeBlockAllocator::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+        unsafe { alloc.dealloc(bytes, layout) };
+    }
+
+    #[test]
+    fn provides_distinct_allocations() {
+        let alloc = FixedSizeBlockAllocator::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let bytes_1 = unsafe { alloc.alloc(layout) };
+        let bytes_2 = unsafe { alloc.alloc(layout) };
+        assert!(!ptr::eq(bytes_1, bytes_2));
+    }
+}
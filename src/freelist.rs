@@ -3,6 +3,11 @@ use std::mem;
 use std::ptr;
 use std::sync::Mutex;
 
+#[cfg(feature = "allocator_api")]
+use std::alloc::{AllocError, Allocator};
+#[cfg(feature = "allocator_api")]
+use std::ptr::NonNull;
+
 pub const DEFAULT_SIZE: usize = 128 * 1024;
 
 pub struct FreeListAlloc<const SIZE: usize> {
@@ -41,6 +46,37 @@ unsafe impl<const SIZE: usize> GlobalAlloc for FreeListAlloc<SIZE> {
             details.dealloc(ptr, layout);
         }
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if let Ok(mut details) = self.details.lock() {
+            details.realloc(ptr, layout, new_size)
+        } else {
+            ptr::null_mut()
+        }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<const SIZE: usize> Allocator for FreeListAlloc<SIZE> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { GlobalAlloc::alloc(self, layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        // adjust_layout's pad_to_align is the actual size we carved out, which may
+        // exceed the request
+        let usable = FreeListImpl::<SIZE>::adjust_layout(layout).pad_to_align().size();
+        Ok(NonNull::slice_from_raw_parts(ptr, usable))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { GlobalAlloc::alloc_zeroed(self, layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        let usable = FreeListImpl::<SIZE>::adjust_layout(layout).pad_to_align().size();
+        Ok(NonNull::slice_from_raw_parts(ptr, usable))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        GlobalAlloc::dealloc(self, ptr.as_ptr(), layout)
+    }
 }
 
 struct ListNode {
@@ -57,22 +93,33 @@ impl ListNode {
         self as *const Self as usize
     }
 
-    fn suitable_for(&self, size: usize) -> bool {
-        // check that the size at this node is enough for the allocation request
-        // also check free memory after allocation location for capability to fit a new ListNode
-        // either there is no free space left, in which case we don't need to add a new node,
-        // or we must fit a new node into the remaining space so that we don't lose track of it
-        size <= self.size
-            && self
-                .size
-                .checked_sub(size)
-                .map(|excess| excess == 0 || excess >= mem::size_of::<ListNode>())
-                .unwrap_or(false)
+    fn end_addr(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+// the arena needs to start out aligned well beyond what ListNode requires, otherwise
+// the very first region we ever hand out could be unable to satisfy a well-aligned
+// request and `fit` would have no choice but to refuse it forever
+#[repr(align(4096))]
+struct Arena<const SIZE: usize>([u8; SIZE]);
+
+impl<const SIZE: usize> Arena<SIZE> {
+    const fn new() -> Self {
+        Self([0x00; SIZE])
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.0.as_mut_ptr()
     }
 }
 
 struct FreeListImpl<const SIZE: usize> {
-    arena: [u8; SIZE],
+    arena: Arena<SIZE>,
     // using option to indicate when initialization has happened
     // not using an actual initialization function because I'm not sure where to call it yet
     // this also seems to get us off nightly rust
@@ -82,7 +129,7 @@ struct FreeListImpl<const SIZE: usize> {
 impl<const SIZE: usize> FreeListImpl<SIZE> {
     const fn new() -> Self {
         Self {
-            arena: [0x00; SIZE],
+            arena: Arena::new(),
             head: None,
         }
     }
@@ -113,18 +160,20 @@ impl<const SIZE: usize> FreeListImpl<SIZE> {
         space
     }
 
-    fn find_region(&mut self, size: usize) -> Option<&'static mut ListNode> {
+    // finds a region that can fit `size` bytes aligned to `align`, returning the popped
+    // node along with the address within it where the allocation should start
+    fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
         let mut prev = self
             .head
             .as_mut()
             .expect("Free list head must be Some() in find_region");
 
         while let Some(ref mut region) = prev.next {
-            if region.suitable_for(size) {
+            if let Some(alloc_start) = Self::fit(region, size, align) {
                 let next = region.next.take();
                 let ret = prev.next.take();
                 prev.next = next;
-                return ret;
+                return ret.map(|node| (node, alloc_start));
             } else {
                 prev = prev.next.as_mut().unwrap();
             }
@@ -132,6 +181,29 @@ impl<const SIZE: usize> FreeListImpl<SIZE> {
         None
     }
 
+    // checks whether `size` bytes aligned to `align` fit inside `node`, and if so, where
+    // the aligned allocation would start. any gap left in front of or behind the
+    // allocation must be either empty or large enough to hold a ListNode of its own,
+    // or we'd lose track of that memory.
+    fn fit(node: &ListNode, size: usize, align: usize) -> Option<usize> {
+        let alloc_start = align_up(node.start_addr(), align);
+        let alloc_end = alloc_start.checked_add(size)?;
+        if alloc_end > node.end_addr() {
+            return None;
+        }
+
+        let list_node_size = mem::size_of::<ListNode>();
+        let front_gap = alloc_start - node.start_addr();
+        let back_gap = node.end_addr() - alloc_end;
+        if (front_gap == 0 || front_gap >= list_node_size)
+            && (back_gap == 0 || back_gap >= list_node_size)
+        {
+            Some(alloc_start)
+        } else {
+            None
+        }
+    }
+
     unsafe fn add_free_region(&mut self, addr: *mut u8, size: usize) {
         // ensure that size and alignment of freed space can fit a ListNode object
         assert!(!addr.is_null(), "Cannot free null pointer");
@@ -145,15 +217,59 @@ impl<const SIZE: usize> FreeListImpl<SIZE> {
             "Freed area must fit a ListNode"
         );
 
-        let mut head = self
+        let start = addr as usize;
+        let end = start + size;
+
+        // walk the list, kept in address order, to find where this region belongs.
+        // the sentinel head isn't a real region, so it can never be merged into.
+        let mut prev_is_head = true;
+        let mut prev = self
             .head
             .as_mut() // don't consume the optional, just modify the value
             .expect("Free list head must be Some() in add_free_region");
+        while let Some(ref next) = prev.next {
+            if next.start_addr() >= start {
+                break;
+            }
+            prev_is_head = false;
+            prev = prev.next.as_mut().unwrap();
+        }
+
+        if !prev_is_head && prev.start_addr() + prev.size == start {
+            // the new region extends prev: grow it in place instead of adding a node
+            prev.size += size;
+            if let Some(next) = prev.next.take() {
+                if prev.start_addr() + prev.size == next.start_addr() {
+                    // prev now touches the following region too: absorb it as well
+                    prev.size += next.size;
+                    prev.next = next.next.take();
+                } else {
+                    prev.next = Some(next);
+                }
+            }
+            return;
+        }
+
+        if let Some(next) = prev.next.take() {
+            if end == next.start_addr() {
+                // the new region touches the following one: write it in place of next,
+                // carrying next's size and successor along with it
+                let mut node = ListNode::new(size + next.size);
+                node.next = next.next.take();
+                let node_ptr = addr as *mut ListNode;
+                node_ptr.write(node);
+                prev.next = Some(&mut *node_ptr);
+                return;
+            }
+            prev.next = Some(next);
+        }
+
+        // no neighbour to merge with: insert a fresh node between prev and prev.next
         let mut node = ListNode::new(size);
-        node.next = head.next.take();
+        node.next = prev.next.take();
         let node_ptr = addr as *mut ListNode;
         node_ptr.write(node);
-        head.next = Some(&mut *node_ptr);
+        prev.next = Some(&mut *node_ptr);
     }
 
     fn adjust_layout(layout: Layout) -> Layout {
@@ -169,18 +285,21 @@ impl<const SIZE: usize> FreeListImpl<SIZE> {
             let start = self.arena.as_mut_ptr();
             self.add_free_region(start, SIZE);
         }
-        let size = Self::adjust_layout(layout).pad_to_align().size();
-        if let Some(node) = self.find_region(size) {
-            let alloc_start = node as *mut ListNode;
-            assert_eq!(alloc_start as usize, node.start_addr());
-            assert!(node.size >= size);
-            let alloc_end = alloc_start.add(size);
-            //if alloc_end as usize > node.end_addr() as usize {
-            //    return ptr::null_mut();
-            //}
-            let excess = node.size - size;
-            if excess > 0 {
-                self.add_free_region(alloc_end as *mut u8, excess);
+        let adjusted = Self::adjust_layout(layout).pad_to_align();
+        let size = adjusted.size();
+        let align = adjusted.align();
+        if let Some((node, alloc_start)) = self.find_region(size, align) {
+            let node_start = node.start_addr();
+            let node_end = node.end_addr();
+            let alloc_end = alloc_start + size;
+            let front_gap = alloc_start - node_start;
+            let back_gap = node_end - alloc_end;
+
+            if front_gap > 0 {
+                self.add_free_region(node_start as *mut u8, front_gap);
+            }
+            if back_gap > 0 {
+                self.add_free_region(alloc_end as *mut u8, back_gap);
             }
             alloc_start as *mut u8
         } else {
@@ -192,6 +311,77 @@ impl<const SIZE: usize> FreeListImpl<SIZE> {
         let size = Self::adjust_layout(layout).pad_to_align().size();
         self.add_free_region(ptr, size);
     }
+
+    // tries to extend an allocation in place by consuming the free region that
+    // immediately follows it. returns None if there's no such region, it isn't big
+    // enough, or what it'd leave behind can't be tracked as a free region of its own.
+    unsafe fn grow(&mut self, ptr: *mut u8, old_size: usize, new_size: usize) -> Option<*mut u8> {
+        let old_end = ptr as usize + old_size;
+        let additional = new_size - old_size;
+
+        let mut prev = self.head.as_mut()?;
+        while let Some(ref next) = prev.next {
+            if next.start_addr() >= old_end {
+                break;
+            }
+            prev = prev.next.as_mut().unwrap();
+        }
+
+        match prev.next {
+            Some(ref next) if next.start_addr() == old_end => {}
+            _ => return None,
+        }
+        let next_size = prev.next.as_ref().unwrap().size;
+        if next_size < additional {
+            return None;
+        }
+
+        let leftover = next_size - additional;
+        if leftover > 0 && leftover < mem::size_of::<ListNode>() {
+            return None;
+        }
+
+        let next = prev.next.take().unwrap();
+        prev.next = next.next.take();
+        if leftover > 0 {
+            self.add_free_region((old_end + additional) as *mut u8, leftover);
+        }
+        Some(ptr)
+    }
+
+    // returns the freed tail of a shrunk allocation to the free list when it's big
+    // enough to track; otherwise the allocation just keeps holding onto it
+    unsafe fn shrink(&mut self, ptr: *mut u8, old_size: usize, new_size: usize) {
+        let freed = old_size - new_size;
+        if freed >= mem::size_of::<ListNode>() {
+            self.add_free_region(ptr.add(new_size), freed);
+        }
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let old_size = Self::adjust_layout(layout).pad_to_align().size();
+        let new_layout = Layout::from_size_align(new_size, layout.align())
+            .expect("Could not construct new layout for realloc");
+        let adjusted_new_size = Self::adjust_layout(new_layout).pad_to_align().size();
+
+        match adjusted_new_size.cmp(&old_size) {
+            std::cmp::Ordering::Equal => ptr,
+            std::cmp::Ordering::Greater => self
+                .grow(ptr, old_size, adjusted_new_size)
+                .unwrap_or_else(|| {
+                    let new_ptr = self.alloc(new_layout);
+                    if !new_ptr.is_null() {
+                        ptr::copy_nonoverlapping(ptr, new_ptr, old_size);
+                        self.dealloc(ptr, layout);
+                    }
+                    new_ptr
+                }),
+            std::cmp::Ordering::Less => {
+                self.shrink(ptr, old_size, adjusted_new_size);
+                ptr
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -269,8 +459,48 @@ mod tests {
                 let bytes = alloc.alloc(layout);
                 alloc.dealloc(bytes, layout);
             }
-            assert_eq!(alloc.free_list_length(), 2);
+            // the freed block is coalesced straight back into its neighbour, so the
+            // free list never grows past a single region
+            assert_eq!(alloc.free_list_length(), 1);
+        }
+    }
+
+    #[test]
+    fn coalesces_adjacent_regions_freed_low_then_high() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(64, 4).unwrap();
+        let block_size = layout.pad_to_align().size();
+        unsafe {
+            let first = alloc.alloc(layout);
+            let second = alloc.alloc(layout);
+            alloc.dealloc(first, layout);
+            alloc.dealloc(second, layout);
         }
+        assert_eq!(alloc.free_list_length(), 1);
+        assert_eq!(alloc.free_space(), DEFAULT_SIZE);
+
+        let wide_layout = Layout::from_size_align(2 * block_size, 4).unwrap();
+        let wide = unsafe { alloc.alloc(wide_layout) };
+        assert!(!wide.is_null());
+    }
+
+    #[test]
+    fn coalesces_adjacent_regions_freed_high_then_low() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(64, 4).unwrap();
+        let block_size = layout.pad_to_align().size();
+        unsafe {
+            let first = alloc.alloc(layout);
+            let second = alloc.alloc(layout);
+            alloc.dealloc(second, layout);
+            alloc.dealloc(first, layout);
+        }
+        assert_eq!(alloc.free_list_length(), 1);
+        assert_eq!(alloc.free_space(), DEFAULT_SIZE);
+
+        let wide_layout = Layout::from_size_align(2 * block_size, 4).unwrap();
+        let wide = unsafe { alloc.alloc(wide_layout) };
+        assert!(!wide.is_null());
     }
 
     #[test]
@@ -288,4 +518,111 @@ mod tests {
             DEFAULT_SIZE - layout2.pad_to_align().size()
         );
     }
+
+    #[test]
+    fn honors_large_alignment() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+        assert_eq!(bytes as usize % 64, 0);
+    }
+
+    #[test]
+    fn honors_alignment_when_region_start_is_misaligned() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        // shift the remaining free region off of a 64-byte boundary
+        let offsetting_layout = Layout::from_size_align(8, 8).unwrap();
+        let _shift = unsafe { alloc.alloc(offsetting_layout) };
+
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+        assert!(!bytes.is_null());
+        assert_eq!(bytes as usize % 64, 0);
+
+        // the front gap needed to reach the alignment, and the back gap left over,
+        // must both still be tracked rather than silently lost
+        let offsetting_size = FreeListImpl::<DEFAULT_SIZE>::adjust_layout(offsetting_layout)
+            .pad_to_align()
+            .size();
+        let alloc_size = FreeListImpl::<DEFAULT_SIZE>::adjust_layout(layout)
+            .pad_to_align()
+            .size();
+        assert_eq!(
+            alloc.free_space(),
+            DEFAULT_SIZE - offsetting_size - alloc_size
+        );
+        assert_eq!(alloc.free_list_length(), 2);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocates_via_allocator_api() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let memory = alloc.allocate(layout).expect("Allocation failed");
+        assert_eq!(
+            memory.len(),
+            FreeListImpl::<DEFAULT_SIZE>::adjust_layout(layout)
+                .pad_to_align()
+                .size()
+        );
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn deallocates_via_allocator_api() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        let memory = alloc.allocate(layout).expect("Allocation failed");
+        unsafe { alloc.deallocate(memory.cast(), layout) };
+        assert_eq!(alloc.free_space(), DEFAULT_SIZE);
+    }
+
+    #[test]
+    fn grows_in_place_when_following_region_is_free() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let (first, second) = unsafe {
+            let first = alloc.alloc(layout);
+            let second = alloc.alloc(layout);
+            (first, second)
+        };
+        unsafe { alloc.dealloc(second, layout) };
+
+        let grown = unsafe { alloc.realloc(first, layout, 72) };
+        assert_eq!(grown, first);
+        assert_eq!(alloc.free_space(), DEFAULT_SIZE - 72);
+    }
+
+    #[test]
+    fn grow_falls_back_to_copy_when_following_region_is_not_free() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let (first, _second) = unsafe {
+            let first = alloc.alloc(layout);
+            let second = alloc.alloc(layout);
+            (first, second)
+        };
+        unsafe { ptr::write_bytes(first, 0xab, 64) };
+
+        let grown = unsafe { alloc.realloc(first, layout, 72) };
+        assert!(!ptr::eq(grown, first));
+        unsafe {
+            for offset in 0..64 {
+                assert_eq!(*grown.add(offset), 0xab);
+            }
+        }
+    }
+
+    #[test]
+    fn shrink_returns_freed_tail_to_free_list() {
+        let alloc = FreeListAlloc::<DEFAULT_SIZE>::new();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let bytes = unsafe { alloc.alloc(layout) };
+
+        let shrunk = unsafe { alloc.realloc(bytes, layout, 32) };
+        assert_eq!(shrunk, bytes);
+        assert_eq!(alloc.free_space(), DEFAULT_SIZE - 32);
+    }
 }
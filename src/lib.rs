@@ -0,0 +1,7 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+pub mod bitmap;
+pub mod bump;
+pub mod fixed_size_block;
+pub mod freelist;
+pub mod null;
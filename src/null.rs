@@ -1,6 +1,11 @@
 use std::alloc::{GlobalAlloc, Layout};
 use std::ptr;
 
+#[cfg(feature = "allocator_api")]
+use std::alloc::{AllocError, Allocator};
+#[cfg(feature = "allocator_api")]
+use std::ptr::NonNull;
+
 pub struct NullAlloc {
 }
 
@@ -20,6 +25,20 @@ unsafe impl GlobalAlloc for NullAlloc {
     }
 }
 
+#[cfg(feature = "allocator_api")]
+unsafe impl Allocator for NullAlloc {
+    fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+
+    fn allocate_zeroed(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -33,4 +52,12 @@ mod tests {
             assert!(bytes.is_null());
         }
     }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocator_api_never_allocates() {
+        let null = NullAlloc::new();
+        let layout = Layout::from_size_align(10, 4).unwrap();
+        assert!(null.allocate(layout).is_err());
+    }
 }